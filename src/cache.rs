@@ -0,0 +1,207 @@
+//! Bounded LRU+TTL cache for ICANN answers, so repeated queries for the same
+//! name don't pay a full round-trip to the upstream resolver.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use simple_dns::Packet;
+
+/**
+ * Normalized question used as a cache key: lowercased name, qtype and qclass.
+ * qtype/qclass are stored as their wire-format u16 codes rather than the
+ * `simple_dns` enums directly, since `QCLASS` doesn't implement `Hash`.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    qname: String,
+    qtype: u16,
+    qclass: u16,
+}
+
+impl CacheKey {
+    fn from_query(query: &[u8]) -> Option<Self> {
+        let packet = Packet::parse(query).ok()?;
+        let question = packet.questions.get(0)?;
+        Some(Self {
+            qname: question.qname.to_string().to_lowercase(),
+            qtype: u16::from(question.qtype),
+            qclass: u16::from(question.qclass),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    reply: Vec<u8>,
+    expires_at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    entries: HashMap<CacheKey, CacheEntry>,
+    /// Usage order, least-recently-used at the front.
+    usage: VecDeque<CacheKey>,
+}
+
+impl Inner {
+    fn touch(&mut self, key: &CacheKey) {
+        if let Some(pos) = self.usage.iter().position(|k| k == key) {
+            self.usage.remove(pos);
+        }
+        self.usage.push_back(key.clone());
+    }
+}
+
+/**
+ * Shared, thread-safe cache of ICANN answers.
+ * Clone to hand each thread its own handle to the same underlying cache.
+ */
+#[derive(Debug)]
+pub struct DnsCache {
+    inner: Arc<Mutex<Inner>>,
+    capacity: usize,
+}
+
+impl DnsCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner::default())),
+            capacity,
+        }
+    }
+
+    /**
+     * Looks up a live answer for `query`, rewriting its id to the query's own id.
+     * Expired entries are dropped lazily on lookup.
+     * Returns the raw upstream reply, not yet tailored to any particular client's EDNS
+     * support: callers must finalize it (attach/strip OPT, set TC) for their own client.
+     */
+    pub fn get(&self, query: &[u8], now: Instant) -> Option<Vec<u8>> {
+        let key = CacheKey::from_query(query)?;
+        let packet = Packet::parse(query).ok()?;
+
+        let mut locked = self.inner.lock().expect("Lock success");
+        let expired = match locked.entries.get(&key) {
+            Some(entry) => entry.expires_at <= now,
+            None => return None,
+        };
+        if expired {
+            locked.entries.remove(&key);
+            if let Some(pos) = locked.usage.iter().position(|k| k == &key) {
+                locked.usage.remove(pos);
+            }
+            return None;
+        }
+
+        locked.touch(&key);
+        let mut reply = locked.entries.get(&key).unwrap().reply.clone();
+        let id_bytes = packet.id().to_be_bytes();
+        reply[0] = id_bytes[0];
+        reply[1] = id_bytes[1];
+        Some(reply)
+    }
+
+    /**
+     * Caches `reply` for the question in `query`, computing the expiry as
+     * `now + min(TTL across answer records)`.
+     * Skips caching when the reply has no answers or a non-NOERROR rcode.
+     * `reply` should be the raw upstream reply, before any client-specific EDNS tailoring,
+     * so later cache hits can each be finalized for their own client.
+     */
+    pub fn insert(&self, query: &[u8], reply: &[u8], now: Instant) {
+        let Some(key) = CacheKey::from_query(query) else {
+            return;
+        };
+        let Ok(reply_packet) = Packet::parse(reply) else {
+            return;
+        };
+        if reply_packet.rcode() != simple_dns::RCODE::NoError || reply_packet.answers.is_empty() {
+            return;
+        }
+        let Some(min_ttl) = reply_packet.answers.iter().map(|rr| rr.ttl).min() else {
+            return;
+        };
+
+        let mut locked = self.inner.lock().expect("Lock success");
+        if !locked.entries.contains_key(&key) && locked.entries.len() >= self.capacity {
+            if let Some(lru) = locked.usage.pop_front() {
+                locked.entries.remove(&lru);
+            }
+        }
+        locked.entries.insert(
+            key.clone(),
+            CacheEntry {
+                reply: reply.to_vec(),
+                expires_at: now + std::time::Duration::from_secs(min_ttl as u64),
+            },
+        );
+        locked.touch(&key);
+    }
+}
+
+impl Clone for DnsCache {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+            capacity: self.capacity,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv4Addr, time::{Duration, Instant}};
+
+    use simple_dns::{rdata::RData, Name, Packet, Question, ResourceRecord, CLASS, QCLASS, QTYPE, TYPE};
+
+    use super::DnsCache;
+
+    fn query_bytes(id: u16, qname: &str) -> Vec<u8> {
+        let question = Question::new(Name::new(qname).unwrap().into_owned(), QTYPE::TYPE(TYPE::A), QCLASS::CLASS(CLASS::IN), false);
+        let mut packet = Packet::new_query(id);
+        packet.questions.push(question);
+        packet.build_bytes_vec().unwrap()
+    }
+
+    fn reply_bytes(id: u16, qname: &str, ttl: u32) -> Vec<u8> {
+        let question = Question::new(Name::new(qname).unwrap().into_owned(), QTYPE::TYPE(TYPE::A), QCLASS::CLASS(CLASS::IN), false);
+        let mut reply = Packet::new_reply(id);
+        reply.questions.push(question);
+        let ip: Ipv4Addr = "127.0.0.1".parse().unwrap();
+        reply.answers.push(ResourceRecord::new(Name::new(qname).unwrap().into_owned(), CLASS::IN, ttl, RData::A(ip.try_into().unwrap())));
+        reply.build_bytes_vec().unwrap()
+    }
+
+    #[test]
+    fn caches_and_returns_a_live_answer_with_the_querys_own_id() {
+        let cache = DnsCache::new(10);
+        let now = Instant::now();
+        cache.insert(&query_bytes(1, "example.com."), &reply_bytes(1, "example.com.", 120), now);
+
+        let hit = cache.get(&query_bytes(42, "example.com."), now).expect("should be cached");
+        assert_eq!(Packet::parse(&hit).unwrap().id(), 42);
+    }
+
+    #[test]
+    fn expires_entries_past_their_ttl() {
+        let cache = DnsCache::new(10);
+        let now = Instant::now();
+        cache.insert(&query_bytes(1, "example.com."), &reply_bytes(1, "example.com.", 1), now);
+
+        assert!(cache.get(&query_bytes(1, "example.com."), now + Duration::from_secs(2)).is_none());
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_entry_once_full() {
+        let cache = DnsCache::new(1);
+        let now = Instant::now();
+        cache.insert(&query_bytes(1, "a.example.com."), &reply_bytes(1, "a.example.com.", 120), now);
+        cache.insert(&query_bytes(1, "b.example.com."), &reply_bytes(1, "b.example.com.", 120), now);
+
+        assert!(cache.get(&query_bytes(1, "a.example.com."), now).is_none(), "the LRU entry should have been evicted");
+        assert!(cache.get(&query_bytes(1, "b.example.com."), now).is_some());
+    }
+}
@@ -1,13 +1,108 @@
 
-use std::{fmt::Debug, error::Error};
+use std::{fmt::Debug, error::Error, net::SocketAddr};
 use dyn_clone::DynClone;
+use simple_dns::{Packet, Question, ResourceRecord};
+
+/**
+ * Structured result of [`CustomHandler::lookup_query`], so handlers don't have to build
+ * raw reply bytes by hand for the common cases.
+ */
+#[derive(Debug, Clone)]
+pub enum LookupOutcome {
+    /// Answer the query authoritatively with these records.
+    Answer(Vec<ResourceRecord<'static>>),
+    /// Answer with NXDOMAIN: no such name.
+    NxDomain,
+    /// Answer with NXDOMAIN, attaching the given records (e.g. a zone's SOA) to the
+    /// authority section so resolvers can cache the negative answer per RFC 2308.
+    NxDomainWithAuthority(Vec<ResourceRecord<'static>>),
+    /// Answer with NOERROR and no answers (the name exists but not with this qtype),
+    /// attaching the given records (e.g. a zone's SOA) to the authority section.
+    NoDataWithAuthority(Vec<ResourceRecord<'static>>),
+    /// Answer with REFUSED.
+    Refused,
+    /// This handler doesn't know about this query; fall back to forwarding it to ICANN.
+    FallbackToIcann,
+}
 
 /**
  * Trait to implement to make AnyDns use a custom handler.
  * Important: Handler must be clonable so it can be used by multiple threads.
+ *
+ * Implement [`CustomHandler::lookup_query`] for the ergonomic, parsed API, or
+ * [`CustomHandler::lookup`] for raw bytes in/out. Each has a default that bridges to the
+ * other, so implementing either one is enough; implementing neither recurses forever.
  */
 pub trait CustomHandler: DynClone + Send {
-    fn lookup(&self, query: &Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>>;
+    /**
+     * Low-level escape hatch: receives and returns raw DNS message bytes.
+     * Default bridges to [`CustomHandler::lookup_query`], using a placeholder client address
+     * since the raw signature doesn't carry one.
+     */
+    fn lookup(&mut self, query: &Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
+        let packet = Packet::parse(query)?;
+        let question = packet.questions.get(0).ok_or("Query has no question")?.clone();
+        let from = SocketAddr::from(([0, 0, 0, 0], 0));
+        let outcome = self.lookup_query(&packet, &question, from)?;
+
+        let mut reply = Packet::new_reply(packet.id());
+        reply.questions.push(question);
+        match outcome {
+            LookupOutcome::Answer(answers) => {
+                for answer in answers {
+                    reply.answers.push(answer);
+                }
+            }
+            LookupOutcome::NxDomain => *reply.rcode_mut() = simple_dns::RCODE::NameError,
+            LookupOutcome::NxDomainWithAuthority(authority) => {
+                *reply.rcode_mut() = simple_dns::RCODE::NameError;
+                for record in authority {
+                    reply.name_servers.push(record);
+                }
+            }
+            LookupOutcome::NoDataWithAuthority(authority) => {
+                for record in authority {
+                    reply.name_servers.push(record);
+                }
+            }
+            LookupOutcome::Refused => *reply.rcode_mut() = simple_dns::RCODE::Refused,
+            LookupOutcome::FallbackToIcann => return Err("Not implemented".into()),
+        };
+        Ok(reply.build_bytes_vec()?)
+    }
+
+    /**
+     * Ergonomic entry point: receives the already-parsed packet/question and the client's
+     * address, and returns a structured [`LookupOutcome`] instead of raw bytes.
+     * The processor assembles the reply, copies the id, and sets flags.
+     * Default bridges to [`CustomHandler::lookup`].
+     */
+    fn lookup_query(&mut self, packet: &Packet, _question: &Question, _from: SocketAddr) -> Result<LookupOutcome, Box<dyn Error>> {
+        let bytes = packet.build_bytes_vec()?;
+        let reply = match self.lookup(&bytes) {
+            Ok(reply) => reply,
+            Err(_) => return Ok(LookupOutcome::FallbackToIcann),
+        };
+        let reply_packet = Packet::parse(&reply)?;
+        let authority: Vec<ResourceRecord<'static>> = reply_packet
+            .name_servers
+            .iter()
+            .map(|record| record.clone().into_owned())
+            .collect();
+        match reply_packet.rcode() {
+            simple_dns::RCODE::NameError if !authority.is_empty() => {
+                Ok(LookupOutcome::NxDomainWithAuthority(authority))
+            }
+            simple_dns::RCODE::NameError => Ok(LookupOutcome::NxDomain),
+            simple_dns::RCODE::Refused => Ok(LookupOutcome::Refused),
+            _ if reply_packet.answers.is_empty() && !authority.is_empty() => {
+                Ok(LookupOutcome::NoDataWithAuthority(authority))
+            }
+            _ => Ok(LookupOutcome::Answer(
+                reply_packet.answers.iter().map(|answer| answer.clone().into_owned()).collect(),
+            )),
+        }
+    }
 }
 
 /**
@@ -37,9 +132,13 @@ impl HandlerHolder {
         HandlerHolder { func: Box::new(f) }
     }
 
-    pub fn call(&self, query: &Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
+    pub fn call(&mut self, query: &Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
         self.func.lookup(query)
     }
+
+    pub fn call_query(&mut self, packet: &Packet, question: &Question, from: SocketAddr) -> Result<LookupOutcome, Box<dyn Error>> {
+        self.func.lookup_query(packet, question, from)
+    }
 }
 
 #[derive(Clone)]
@@ -53,17 +152,19 @@ impl EmptyHandler {
 }
 
 impl CustomHandler for EmptyHandler {
-    fn lookup(&self, query: &Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
-        Err("Not implemented".into())
+    fn lookup_query(&mut self, _packet: &Packet, _question: &Question, _from: SocketAddr) -> Result<LookupOutcome, Box<dyn Error>> {
+        Ok(LookupOutcome::FallbackToIcann)
     }
 }
 
 
 #[cfg(test)]
 mod tests {
-    use std::error::Error;
+    use std::{error::Error, net::SocketAddr};
 
-    use crate::custom_handler::EmptyHandler;
+    use simple_dns::{Packet, Question};
+
+    use crate::custom_handler::{EmptyHandler, LookupOutcome};
 
     use super::{HandlerHolder, CustomHandler};
 
@@ -87,20 +188,20 @@ mod tests {
             TestHandler{value: ClonableStruct{value: value.to_string()}}
         }
     }
-    
+
     impl CustomHandler for TestHandler {
-        fn lookup(&self, query: &Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
+        fn lookup_query(&mut self, _packet: &Packet, _question: &Question, _from: SocketAddr) -> Result<LookupOutcome, Box<dyn Error>> {
             println!("value {}", self.value.value);
-            Err("Not implemented".into())
+            Ok(LookupOutcome::FallbackToIcann)
         }
     }
 
 
     #[test]
     fn run_processor() {
-        let mut test1 = TestHandler::new("test1");
+        let test1 = TestHandler::new("test1");
         let holder1 = HandlerHolder::new(test1);
-        let cloned = holder1.clone();
+        let mut cloned = holder1.clone();
         let result = cloned.call(&vec![]);
         assert!(result.is_err());
 
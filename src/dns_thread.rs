@@ -11,9 +11,12 @@ use std::{
 use simple_dns::Packet;
 
 use crate::{
-    custom_handler::HandlerHolder,
+    cache::DnsCache,
+    custom_handler::{HandlerHolder, LookupOutcome},
+    edns,
     error::{Error, Result},
     pending_queries::{PendingQuery, ThreadSafeStore},
+    resolver_pool::ResolverPool,
 };
 
 
@@ -34,11 +37,17 @@ pub enum ProcessingError {
 pub struct DnsProcessor {
     pending_queries: ThreadSafeStore,
     socket: UdpSocket,
-    icann_resolver: SocketAddr,
+    resolvers: ResolverPool,
+    timeout: Duration,
+    last_timeout_sweep: Instant,
+    max_age: Duration,
+    last_stale_sweep: Instant,
     next_id: u16,
     id_range: Range<u16>,
     stop_signal: Arc<AtomicBool>,
     handler: HandlerHolder,
+    cache: DnsCache,
+    edns_udp_payload_size: u16,
     verbose: bool
 }
 
@@ -47,26 +56,39 @@ impl DnsProcessor {
      * Creates a new thread safe dns processor.
      * `socket` is a socket handler.
      * `pending_queries` must be a `PendingStore::ThreadSafe` store, otherwise udp packets will be missed.
-     * `id_range` is a range of dns packet ids this thread can use to send to `icann_resolver`.
+     * `id_range` is a range of dns packet ids this thread can use to send to the ICANN resolvers.
+     * `timeout` is how long to wait for an upstream answer before retrying the next resolver.
      * `handler` custom packet handler.
+     * `edns_udp_payload_size` is the UDP payload size advertised in our own OPT records,
+     * and the size the receive buffer is allocated with.
      */
     pub fn new_threadsafe(
         socket: UdpSocket,
-        icann_resolver: SocketAddr,
+        resolvers: ResolverPool,
+        timeout: Duration,
+        max_age: Duration,
         pending_queries: ThreadSafeStore,
         id_range: Range<u16>,
         stop_signal: Arc<AtomicBool>,
         handler: HandlerHolder,
+        cache: DnsCache,
+        edns_udp_payload_size: u16,
         verbose: bool
     ) -> Self {
         DnsProcessor {
             socket,
             pending_queries,
-            icann_resolver,
+            resolvers,
+            timeout,
+            last_timeout_sweep: Instant::now(),
+            max_age,
+            last_stale_sweep: Instant::now(),
             id_range: id_range.clone(),
             next_id: id_range.start,
             stop_signal,
             handler,
+            cache,
+            edns_udp_payload_size,
             verbose
         }
     }
@@ -83,7 +105,7 @@ impl DnsProcessor {
     /**
      * Receives data from the socket. Honors the timeout so the server can be stopped by the stop signal.
      */
-    fn recv_from(&self, buffer: &mut [u8; 1024]) -> Result<(usize, SocketAddr), ProcessingError> {
+    fn recv_from(&self, buffer: &mut [u8]) -> Result<(usize, SocketAddr), ProcessingError> {
         loop {
             match self.socket.recv_from(buffer) {
                 Ok((size, from)) => {
@@ -116,27 +138,124 @@ impl DnsProcessor {
     }
 
     /**
-     * Forward query to icann
+     * Forward query to an upstream ICANN resolver.
      */
     fn forward_to_icann(&mut self, mut query: Vec<u8>, from: SocketAddr) -> Result<(), ProcessingError> {
         let received = Instant::now();
-        let packet = Packet::parse(&query).unwrap();
+        let client_has_edns = edns::has_edns(&Packet::parse(&query).unwrap());
         let id = self.next_id();
+        let resolver_index = self.resolvers.pick();
         self.pending_queries.insert(PendingQuery {
             icann_id: id,
             query: query.to_vec(),
             from,
             received_at: received,
+            last_sent_at: received,
+            resolver_index,
         });
 
         let id_bytes = id.to_be_bytes();
         query[0] = id_bytes[0];
         query[1] = id_bytes[1];
 
-        self.socket.send_to(&query, self.icann_resolver)?;
+        // Advertise our own payload size upstream, so resolvers that support EDNS0 can send
+        // back a single larger UDP answer instead of forcing us into a truncation round trip.
+        if !client_has_edns {
+            if let Ok(mut edns_query) = Packet::parse(&query) {
+                edns_query.additional_records.push(edns::build_opt_record(self.edns_udp_payload_size));
+                if let Ok(bytes) = edns_query.build_bytes_vec() {
+                    query = bytes;
+                }
+            }
+        }
+
+        self.socket.send_to(&query, self.resolvers.address(resolver_index))?;
         Ok(())
     }
 
+    /**
+     * Re-forwards any query that hasn't heard back within `timeout` to the next
+     * resolver in the list, marking resolvers as dead after enough consecutive timeouts.
+     * Throttled so it only runs once per `timeout` interval, not on every packet.
+     */
+    fn sweep_timeouts(&mut self) {
+        let now = Instant::now();
+        if now.duration_since(self.last_timeout_sweep) < self.timeout {
+            return;
+        }
+        self.last_timeout_sweep = now;
+
+        for pending in self.pending_queries.timed_out(now, self.timeout) {
+            self.resolvers.record_timeout(pending.resolver_index);
+            let next_index = self.resolvers.next(pending.resolver_index);
+
+            let mut retry = pending.query.clone();
+            let id_bytes = pending.icann_id.to_be_bytes();
+            retry[0] = id_bytes[0];
+            retry[1] = id_bytes[1];
+
+            if self.socket.send_to(&retry, self.resolvers.address(next_index)).is_ok() {
+                self.pending_queries.mark_retried(&pending.icann_id, now, next_index);
+            }
+        }
+    }
+
+    /**
+     * Evicts queries that have been pending for longer than `max_age`, so a dead or
+     * packet-losing upstream can't leak memory or leave a client hanging forever.
+     * Each evicted query gets a synthesized SERVFAIL reply instead of silence.
+     * Throttled so it only runs once per `max_age` interval, not on every packet.
+     */
+    fn sweep_stale(&mut self) {
+        let now = Instant::now();
+        if now.duration_since(self.last_stale_sweep) < self.max_age {
+            return;
+        }
+        self.last_stale_sweep = now;
+
+        for pending in self.pending_queries.sweep(now, self.max_age) {
+            if let Err(err) = self.send_servfail(&pending) {
+                if self.verbose {
+                    eprintln!("Failed to send SERVFAIL {}", err);
+                }
+            }
+        }
+    }
+
+    /** Synthesizes a SERVFAIL reply to the original question and sends it back to the client. */
+    fn send_servfail(&self, pending: &PendingQuery) -> Result<(), ProcessingError> {
+        let query_packet = Packet::parse(&pending.query).unwrap();
+        let mut reply = Packet::new_reply(query_packet.id());
+        for question in query_packet.questions.iter() {
+            reply.questions.push(question.clone());
+        }
+        *reply.rcode_mut() = simple_dns::RCODE::ServerFailure;
+
+        let client_has_edns = edns::has_edns(&query_packet);
+        let bytes = self.finalize_udp_reply(reply, client_has_edns);
+        self.socket.send_to(&bytes, pending.from)?;
+        Ok(())
+    }
+
+    /**
+     * Attaches our own OPT record when the client advertised EDNS0 (dropping any upstream
+     * one already present), and otherwise sets the TC bit if the reply is too big for the
+     * classic 512-byte UDP limit, so a non-EDNS client falls back to retrying over TCP.
+     */
+    fn finalize_udp_reply(&self, mut reply: Packet, client_has_edns: bool) -> Vec<u8> {
+        reply.additional_records.retain(|record| !matches!(record.rdata, simple_dns::rdata::RData::OPT(_)));
+        if client_has_edns {
+            reply.additional_records.push(edns::build_opt_record(self.edns_udp_payload_size));
+        }
+
+        let bytes = reply.build_bytes_vec().unwrap_or_default();
+        if !client_has_edns && bytes.len() > edns::CLASSIC_UDP_PAYLOAD_SIZE {
+            reply.set_flags(simple_dns::PacketFlag::TRUNCATION);
+            return reply.build_bytes_vec().unwrap_or(bytes);
+        }
+        bytes
+    }
+
     /**
      * Send answers to client.
      */
@@ -151,11 +270,35 @@ impl DnsProcessor {
         }
 
         let pending = removed_opt.unwrap();
+        self.resolvers.record_success(pending.resolver_index);
+
+        // The answer didn't fit in the UDP datagram; retry over TCP so the client gets the full answer.
+        if reply_packet.has_flags(simple_dns::PacketFlag::TRUNCATION) {
+            let resolver = self.resolvers.address(pending.resolver_index);
+            match crate::tcp_thread::forward_to_icann_tcp(&resolver, &pending.query) {
+                Ok(tcp_reply) => reply = tcp_reply,
+                Err(err) => {
+                    if self.verbose {
+                        eprintln!("TCP retry error {}", err);
+                    }
+                }
+            }
+        }
+
         let pending_packet = Packet::parse(&pending.query).unwrap();
-        let id_bytes = pending_packet.id().to_be_bytes();
         reply[0] = pending.query[0];
         reply[1] = pending.query[1];
 
+        // Cache the raw upstream reply before any client-specific EDNS tailoring, so a later
+        // cache hit can be finalized for whichever client asks next instead of replaying bytes
+        // tailored to this client's EDNS support.
+        self.cache.insert(&pending.query, &reply, Instant::now());
+
+        if let Ok(reply_packet) = Packet::parse(&reply) {
+            let client_has_edns = edns::has_edns(&pending_packet);
+            reply = self.finalize_udp_reply(reply_packet, client_has_edns);
+        }
+
         self.socket
             .send_to(&reply, pending.from)?;
 
@@ -175,19 +318,85 @@ impl DnsProcessor {
 
     /** Receive and process one udp packet.  */
     fn process_packet(&mut self) -> Result<(), ProcessingError> {
-        let mut buffer = [0; 1024];
+        let mut buffer = vec![0u8; self.edns_udp_payload_size as usize];
         let (size, from) = self.recv_from(&mut buffer)?;
         let query = buffer[..size].to_vec();
-        if from == self.icann_resolver {
+        if self.resolvers.contains(&from) {
             self.respond_to_client(query)?;
         } else {
-            let result = self.handler.call(&query);
-            if result.is_ok() {
-                self.respond_to_client(result.unwrap())?;
-            } else {
-                self.forward_to_icann(query, from)?;
+            self.handle_client_query(query, from)?;
+        }
+        Ok(())
+    }
+
+    /**
+     * Handles a query from a client: asks the custom handler for a structured outcome,
+     * assembling the reply ourselves so handlers don't have to build raw bytes by hand.
+     * Falls back to the cache, then to forwarding upstream, when the handler has no answer.
+     */
+    fn handle_client_query(&mut self, query: Vec<u8>, from: SocketAddr) -> Result<(), ProcessingError> {
+        let Ok(packet) = Packet::parse(&query) else {
+            return Ok(());
+        };
+        let Some(question) = packet.questions.get(0).cloned() else {
+            return Ok(());
+        };
+
+        let client_has_edns = edns::has_edns(&packet);
+        let outcome = self.handler.call_query(&packet, &question, from);
+        let answer_bytes = match outcome {
+            Ok(LookupOutcome::Answer(answers)) => {
+                let mut reply = Packet::new_reply(packet.id());
+                reply.questions.push(question);
+                for answer in answers {
+                    reply.answers.push(answer);
+                }
+                Some(self.finalize_udp_reply(reply, client_has_edns))
             }
+            Ok(LookupOutcome::NxDomain) => {
+                let mut reply = Packet::new_reply(packet.id());
+                reply.questions.push(question);
+                *reply.rcode_mut() = simple_dns::RCODE::NameError;
+                Some(self.finalize_udp_reply(reply, client_has_edns))
+            }
+            Ok(LookupOutcome::NxDomainWithAuthority(authority)) => {
+                let mut reply = Packet::new_reply(packet.id());
+                reply.questions.push(question);
+                *reply.rcode_mut() = simple_dns::RCODE::NameError;
+                for record in authority {
+                    reply.name_servers.push(record);
+                }
+                Some(self.finalize_udp_reply(reply, client_has_edns))
+            }
+            Ok(LookupOutcome::NoDataWithAuthority(authority)) => {
+                let mut reply = Packet::new_reply(packet.id());
+                reply.questions.push(question);
+                for record in authority {
+                    reply.name_servers.push(record);
+                }
+                Some(self.finalize_udp_reply(reply, client_has_edns))
+            }
+            Ok(LookupOutcome::Refused) => {
+                let mut reply = Packet::new_reply(packet.id());
+                reply.questions.push(question);
+                *reply.rcode_mut() = simple_dns::RCODE::Refused;
+                Some(self.finalize_udp_reply(reply, client_has_edns))
+            }
+            Ok(LookupOutcome::FallbackToIcann) | Err(_) => None,
+        };
 
+        if let Some(bytes) = answer_bytes {
+            self.socket.send_to(&bytes, from)?;
+        } else if let Some(cached) = self.cache.get(&query, Instant::now()) {
+            // The cache holds the raw upstream reply; finalize it for this client's own EDNS
+            // support rather than replaying bytes tailored to whichever client populated it.
+            let cached = match Packet::parse(&cached) {
+                Ok(cached_packet) => self.finalize_udp_reply(cached_packet, client_has_edns),
+                Err(_) => cached,
+            };
+            self.socket.send_to(&cached, from)?;
+        } else {
+            self.forward_to_icann(query, from)?;
         }
         Ok(())
     }
@@ -197,6 +406,8 @@ impl DnsProcessor {
      */
     pub fn run(&mut self) -> Result<()> {
         loop {
+            self.sweep_timeouts();
+            self.sweep_stale();
             let result = self.process_packet();
             if result.is_ok() {
                 continue;
@@ -231,22 +442,29 @@ impl DnsThread {
      */
     pub fn new(
         socket: &UdpSocket,
-        icann_resolver: &SocketAddr,
+        resolvers: &ResolverPool,
+        timeout: Duration,
+        max_age: Duration,
         pending_queries: &ThreadSafeStore,
         id_range: Range<u16>,
         handler: &HandlerHolder,
+        cache: &DnsCache,
+        edns_udp_payload_size: u16,
         verbose: bool
     ) -> Self {
         let socket = socket.try_clone().expect("Should clone");
-        let icann_resolver = icann_resolver.clone();
         let stop_signal = Arc::new(AtomicBool::new(false));
         let mut processor = DnsProcessor::new_threadsafe(
             socket,
-            icann_resolver,
+            resolvers.clone(),
+            timeout,
+            max_age,
             pending_queries.clone(),
             id_range,
             stop_signal.clone(),
             handler.clone(),
+            cache.clone(),
+            edns_udp_payload_size,
             verbose
         );
         let thread_work = std::thread::spawn(move || processor.run());
@@ -0,0 +1,57 @@
+//! Minimal EDNS0 (OPT pseudo-record) support: reading a client's advertised UDP payload
+//! size and attaching our own to forwarded queries and synthesized replies.
+
+use simple_dns::{rdata::{RData, OPT}, Name, Packet, ResourceRecord, CLASS};
+
+/// Default payload size advertised when `Builder::edns_udp_payload_size` isn't set.
+pub const DEFAULT_EDNS_UDP_PAYLOAD_SIZE: u16 = 1232;
+
+/// The historic UDP limit a reply is capped at until a client opts into EDNS0.
+pub const CLASSIC_UDP_PAYLOAD_SIZE: usize = 512;
+
+/// True if `packet` carries an OPT pseudo-record, i.e. the sender supports EDNS0.
+pub fn has_edns(packet: &Packet) -> bool {
+    packet.additional_records.iter().any(|record| matches!(record.rdata, RData::OPT(_)))
+}
+
+/// Builds an OPT pseudo-record advertising `payload_size`, with no extended flags set.
+/// The advertised size is carried in `OPT::udp_packet_size`, not the RR's `class` field:
+/// `simple_dns` serializes an OPT record's class bytes from `udp_packet_size` directly,
+/// so the `class` we set here is never actually written to the wire.
+pub fn build_opt_record(payload_size: u16) -> ResourceRecord<'static> {
+    ResourceRecord::new(
+        Name::new_unchecked("."),
+        CLASS::IN,
+        0,
+        RData::OPT(OPT {
+            opt_codes: Vec::new(),
+            udp_packet_size: payload_size,
+            version: 0,
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use simple_dns::{rdata::RData, Packet};
+
+    use super::{build_opt_record, has_edns, DEFAULT_EDNS_UDP_PAYLOAD_SIZE};
+
+    #[test]
+    fn detects_opt_record_as_edns() {
+        let mut packet = Packet::new_query(1);
+        assert!(!has_edns(&packet));
+
+        packet.additional_records.push(build_opt_record(DEFAULT_EDNS_UDP_PAYLOAD_SIZE));
+        assert!(has_edns(&packet));
+    }
+
+    #[test]
+    fn opt_record_advertises_payload_size() {
+        let record = build_opt_record(4096);
+        match record.rdata {
+            RData::OPT(opt) => assert_eq!(opt.udp_packet_size, 4096),
+            _ => panic!("expected an OPT rdata"),
+        }
+    }
+}
@@ -2,10 +2,16 @@
 
 pub mod error;
 pub mod server;
+pub mod zone;
+mod cache;
 mod dns_thread;
 mod custom_handler;
+mod edns;
 mod pending_queries;
+mod resolver_pool;
+mod tcp_thread;
 
 pub use crate::error::{Error, Result};
 pub use crate::server::{AnyDNS, Builder};
-pub use crate::custom_handler::{CustomHandler};
\ No newline at end of file
+pub use crate::custom_handler::{CustomHandler, LookupOutcome};
+pub use crate::zone::{Soa, Zone, ZoneHandler, ZoneRecord};
\ No newline at end of file
@@ -1,17 +1,9 @@
 #![allow(unused)]
 
-mod error;
-mod server;
-mod dns_thread;
-mod pending_queries;
-mod custom_handler;
+use std::{cmp::Ordering, error::Error, net::{Ipv4Addr, SocketAddr}, sync::{atomic::AtomicBool, Arc}, thread::sleep, time::Duration};
 
-use std::{cmp::Ordering, error::Error, net::Ipv4Addr, sync::{atomic::AtomicBool, Arc}, thread::sleep, time::Duration};
-
-use any_dns::{CustomHandler, Builder};
-use error::Result;
-use server::AnyDNS;
-use simple_dns::{Packet, PacketFlag, ResourceRecord, QTYPE};
+use any_dns::{CustomHandler, Builder, Result};
+use simple_dns::{Packet, Question, ResourceRecord, QTYPE};
 
 #[derive(Clone, Debug)]
 struct MyHandler {}
@@ -20,19 +12,14 @@ impl CustomHandler for MyHandler {
     /**
      * Only resolve 1 custom domain 7fmjpcuuzf54hw18bsgi3zihzyh4awseeuq5tmojefaezjbd64cy.
      */
-    fn lookup(&mut self, query: &Vec<u8>) -> std::prelude::v1::Result<Vec<u8>, Box<dyn Error>> {
-        let packet = Packet::parse(query).unwrap();
-        let question = packet.questions.get(0).expect("Valid query");
+    fn lookup_query(&mut self, _packet: &Packet, question: &Question, _from: SocketAddr) -> std::prelude::v1::Result<any_dns::LookupOutcome, Box<dyn Error>> {
         if question.qname.to_string() != "7fmjpcuuzf54hw18bsgi3zihzyh4awseeuq5tmojefaezjbd64cy" || question.qtype != QTYPE::TYPE(simple_dns::TYPE::A) {
-            return Err("Not Implemented".into());
+            return Ok(any_dns::LookupOutcome::FallbackToIcann);
         };
 
-        let mut reply = Packet::new_reply(packet.id());
-        reply.questions.push(question.clone());
         let ip: Ipv4Addr = "37.27.13.182".parse().unwrap();
         let record = ResourceRecord::new(question.qname.clone(), simple_dns::CLASS::IN, 120, simple_dns::rdata::RData::A(ip.try_into().unwrap()));
-        reply.answers.push(record);
-        Ok(reply.build_bytes_vec().unwrap())
+        Ok(any_dns::LookupOutcome::Answer(vec![record]))
     }
 }
 
@@ -1,11 +1,13 @@
-use std::{net::SocketAddr, time::Instant, collections::HashMap, sync::{Mutex, Arc}};
+use std::{net::SocketAddr, time::{Duration, Instant}, collections::HashMap, sync::{Mutex, Arc}};
 
 #[derive(Debug, Clone)]
 pub struct PendingQuery {
     pub from: SocketAddr,
     pub query: Vec<u8>,
     pub received_at: Instant,
+    pub last_sent_at: Instant,
     pub icann_id: u16,
+    pub resolver_index: usize,
 }
 
 /**
@@ -43,21 +45,57 @@ pub struct ThreadSafeStore {
 }
 
 impl ThreadSafeStore {
-    fn insert(&mut self, query: PendingQuery) {
+    pub(crate) fn insert(&mut self, query: PendingQuery) {
         let mut locked = self.pending_queries.lock().expect("Lock success");
         locked.insert(query.icann_id, query);
     }
 
-    fn remove(&mut self, id: &u16) -> Option<PendingQuery> {
+    pub(crate) fn remove(&mut self, id: &u16) -> Option<PendingQuery> {
         let mut locked = self.pending_queries.lock().expect("Lock success");
         locked.remove(id)
     }
 
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             pending_queries: Arc::new(Mutex::new(HashMap::new()))
         }
     }
+
+    /** Snapshot of entries that haven't been (re)sent in over `timeout`, eligible for a retry against the next resolver. */
+    pub(crate) fn timed_out(&self, now: Instant, timeout: Duration) -> Vec<PendingQuery> {
+        let locked = self.pending_queries.lock().expect("Lock success");
+        locked
+            .values()
+            .filter(|query| now.duration_since(query.last_sent_at) > timeout)
+            .cloned()
+            .collect()
+    }
+
+    /** Updates bookkeeping for a query that is being retried against `resolver_index`. */
+    pub(crate) fn mark_retried(&mut self, id: &u16, now: Instant, resolver_index: usize) {
+        let mut locked = self.pending_queries.lock().expect("Lock success");
+        if let Some(query) = locked.get_mut(id) {
+            query.last_sent_at = now;
+            query.resolver_index = resolver_index;
+        }
+    }
+
+    /**
+     * Removes and returns entries that have been pending for longer than `max_age`,
+     * so upstreams that never answer don't leak memory or leave clients hanging forever.
+     */
+    pub(crate) fn sweep(&mut self, now: Instant, max_age: Duration) -> Vec<PendingQuery> {
+        let mut locked = self.pending_queries.lock().expect("Lock success");
+        let stale_ids: Vec<u16> = locked
+            .values()
+            .filter(|query| now.duration_since(query.received_at) > max_age)
+            .map(|query| query.icann_id)
+            .collect();
+        stale_ids
+            .iter()
+            .filter_map(|id| locked.remove(id))
+            .collect()
+    }
 }
 
 impl Clone for ThreadSafeStore {
@@ -0,0 +1,123 @@
+//! Ordered list of upstream ICANN resolvers with basic failover bookkeeping.
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+/// Consecutive per-query timeouts after which a resolver is considered dead.
+const MAX_CONSECUTIVE_TIMEOUTS: u32 = 5;
+
+#[derive(Debug, Clone)]
+struct ResolverState {
+    address: SocketAddr,
+    consecutive_timeouts: u32,
+}
+
+/**
+ * Shared, thread-safe list of upstream resolvers.
+ * Clone to hand each thread its own handle to the same underlying list.
+ */
+#[derive(Debug)]
+pub struct ResolverPool {
+    resolvers: Arc<Mutex<Vec<ResolverState>>>,
+}
+
+impl ResolverPool {
+    pub fn new(addresses: Vec<SocketAddr>) -> Self {
+        assert!(!addresses.is_empty(), "At least one ICANN resolver is required");
+        let resolvers = addresses
+            .into_iter()
+            .map(|address| ResolverState { address, consecutive_timeouts: 0 })
+            .collect();
+        Self { resolvers: Arc::new(Mutex::new(resolvers)) }
+    }
+
+    /// Address of the resolver at `index`, wrapping around the list.
+    pub fn address(&self, index: usize) -> SocketAddr {
+        let locked = self.resolvers.lock().expect("Lock success");
+        locked[index % locked.len()].address
+    }
+
+    /// True if `address` belongs to one of the configured resolvers.
+    pub fn contains(&self, address: &SocketAddr) -> bool {
+        let locked = self.resolvers.lock().expect("Lock success");
+        locked.iter().any(|resolver| &resolver.address == address)
+    }
+
+    /// Picks the first resolver that isn't currently marked dead, defaulting to index 0.
+    pub fn pick(&self) -> usize {
+        let locked = self.resolvers.lock().expect("Lock success");
+        locked
+            .iter()
+            .position(|resolver| resolver.consecutive_timeouts < MAX_CONSECUTIVE_TIMEOUTS)
+            .unwrap_or(0)
+    }
+
+    /// Index of the resolver to retry after `index` timed out.
+    pub fn next(&self, index: usize) -> usize {
+        let locked = self.resolvers.lock().expect("Lock success");
+        (index + 1) % locked.len()
+    }
+
+    /// Records a timeout against the resolver at `index`.
+    pub fn record_timeout(&self, index: usize) {
+        let mut locked = self.resolvers.lock().expect("Lock success");
+        let len = locked.len();
+        locked[index % len].consecutive_timeouts += 1;
+    }
+
+    /// Clears the timeout counter for a resolver once it answers successfully.
+    pub fn record_success(&self, index: usize) {
+        let mut locked = self.resolvers.lock().expect("Lock success");
+        let len = locked.len();
+        locked[index % len].consecutive_timeouts = 0;
+    }
+}
+
+impl Clone for ResolverPool {
+    fn clone(&self) -> Self {
+        Self { resolvers: Arc::clone(&self.resolvers) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ResolverPool, MAX_CONSECUTIVE_TIMEOUTS};
+
+    fn addresses(n: u16) -> Vec<std::net::SocketAddr> {
+        (0..n).map(|i| std::net::SocketAddr::from(([127, 0, 0, 1], 10000 + i))).collect()
+    }
+
+    #[test]
+    fn picks_the_first_resolver_by_default() {
+        let pool = ResolverPool::new(addresses(2));
+        assert_eq!(pool.pick(), 0);
+    }
+
+    #[test]
+    fn marks_a_resolver_dead_after_enough_consecutive_timeouts() {
+        let pool = ResolverPool::new(addresses(2));
+
+        for _ in 0..MAX_CONSECUTIVE_TIMEOUTS {
+            pool.record_timeout(0);
+        }
+
+        assert_eq!(pool.pick(), 1, "the timed-out resolver should be skipped once dead");
+    }
+
+    #[test]
+    fn record_success_resets_the_timeout_counter() {
+        let pool = ResolverPool::new(addresses(2));
+
+        for _ in 0..MAX_CONSECUTIVE_TIMEOUTS - 1 {
+            pool.record_timeout(0);
+        }
+        pool.record_success(0);
+        for _ in 0..MAX_CONSECUTIVE_TIMEOUTS - 1 {
+            pool.record_timeout(0);
+        }
+
+        assert_eq!(pool.pick(), 0, "a resolver that recovered shouldn't stay marked dead");
+    }
+}
@@ -6,32 +6,73 @@ use std::{
     net::{SocketAddr, UdpSocket}, str::FromStr, thread::sleep, time::{Duration, Instant}, sync::{Arc, Mutex}, ops::Range,
 };
 
-use crate::{dns_thread::DnsThread, pending_queries::{self, PendingQuery, ThreadSafeStore}, custom_handler::{HandlerHolder, EmptyHandler, CustomHandler}};
+use crate::{cache::DnsCache, dns_thread::DnsThread, edns, pending_queries::{self, PendingQuery, ThreadSafeStore}, custom_handler::{HandlerHolder, EmptyHandler, CustomHandler}, resolver_pool::ResolverPool, tcp_thread::TcpThread};
 
 
 
+/// Default number of answers kept in the response cache.
+const DEFAULT_CACHE_SIZE: usize = 1000;
+
+/// Default per-query timeout before retrying the next upstream resolver.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Default max age a query can stay pending before it is evicted with a SERVFAIL reply.
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(5);
+
 pub struct Builder {
-    icann_resolver: SocketAddr,
+    icann_resolvers: Vec<SocketAddr>,
     listen: SocketAddr,
     thread_count: u8,
     handler: HandlerHolder,
-    verbose: bool
+    verbose: bool,
+    cache_size: usize,
+    listen_tcp: bool,
+    timeout: Duration,
+    max_age: Duration,
+    edns_udp_payload_size: u16
 }
 
 impl Builder {
     pub fn new() -> Self {
         Self {
-            icann_resolver: SocketAddr::from(([192, 168, 1, 1], 53)),
+            icann_resolvers: vec![SocketAddr::from(([192, 168, 1, 1], 53))],
             listen: SocketAddr::from(([0, 0, 0, 0], 53)),
             thread_count: 1,
             handler: HandlerHolder::new(EmptyHandler::new()),
-            verbose: false
+            verbose: false,
+            cache_size: DEFAULT_CACHE_SIZE,
+            listen_tcp: false,
+            timeout: DEFAULT_TIMEOUT,
+            max_age: DEFAULT_MAX_AGE,
+            edns_udp_payload_size: edns::DEFAULT_EDNS_UDP_PAYLOAD_SIZE
         }
     }
 
-    /// Set the DNS resolver for normal ICANN domains. Defaults to 192.168.1.1:53
+    /// Set the DNS resolver for normal ICANN domains. Defaults to 192.168.1.1:53.
+    /// Sugar for `icann_resolvers(vec![icann_resolver])`.
     pub fn icann_resolver(mut self, icann_resolver: SocketAddr) -> Self {
-        self.icann_resolver = icann_resolver;
+        self.icann_resolvers = vec![icann_resolver];
+        self
+    }
+
+    /// Set an ordered list of ICANN resolvers. The first is tried before falling back to the
+    /// next ones if it times out or is marked dead.
+    pub fn icann_resolvers(mut self, icann_resolvers: Vec<SocketAddr>) -> Self {
+        self.icann_resolvers = icann_resolvers;
+        self
+    }
+
+    /// Set how long to wait for an answer from an upstream resolver before retrying the next
+    /// one in the list. Defaults to 2 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set how long a query can stay pending before it is given up on and evicted with a
+    /// synthesized SERVFAIL reply. Defaults to 5 seconds.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
         self
     }
 
@@ -59,20 +100,49 @@ impl Builder {
         self
     }
 
+    /// Set the maximum number of answers kept in the response cache. Defaults to 1000.
+    pub fn cache_size(mut self, cache_size: usize) -> Self {
+        self.cache_size = cache_size;
+        self
+    }
+
+    /// Also listen for DNS-over-TCP on the same address as [Builder::listen]. Defaults to false.
+    pub fn listen_tcp(mut self, listen_tcp: bool) -> Self {
+        self.listen_tcp = listen_tcp;
+        self
+    }
+
+    /// Set the UDP payload size we advertise via EDNS0 (RFC 6891), used both to size the
+    /// receive buffer and in the OPT record attached to forwarded queries and synthesized
+    /// replies. Defaults to 1232, the size recommended by the DNS Flag Day project.
+    pub fn edns_udp_payload_size(mut self, edns_udp_payload_size: u16) -> Self {
+        self.edns_udp_payload_size = edns_udp_payload_size;
+        self
+    }
+
     pub fn build(self) -> AnyDNS {
         let socket = UdpSocket::bind(self.listen).expect("Address available");
         socket.set_read_timeout(Some(Duration::from_millis(500))); // So the DNS can be stopped.
         let pending_queries = ThreadSafeStore::new();
+        let cache = DnsCache::new(self.cache_size);
+        let resolvers = ResolverPool::new(self.icann_resolvers);
         let mut threads = vec![];
         for i in 0..self.thread_count {
             let id_range = Self::calculate_id_range(self.thread_count as u16, i as u16);
-            let thread = DnsThread::new(&socket, &self.icann_resolver, &pending_queries, id_range, self.handler.clone(), self.verbose);
+            let thread = DnsThread::new(&socket, &resolvers, self.timeout, self.max_age, &pending_queries, id_range, &self.handler, &cache, self.edns_udp_payload_size, self.verbose);
             threads.push(thread);
         }
 
+        let tcp_thread = if self.listen_tcp {
+            Some(TcpThread::new(self.listen, resolvers.clone(), self.handler.clone(), self.verbose).expect("TCP address available"))
+        } else {
+            None
+        };
+
         AnyDNS {
             threads,
-            icann_resolver: self.icann_resolver
+            tcp_thread,
+            resolvers
         }
     }
 
@@ -87,8 +157,9 @@ impl Builder {
 
 #[derive(Debug)]
 pub struct AnyDNS {
-    icann_resolver: SocketAddr,
+    resolvers: ResolverPool,
     threads: Vec<DnsThread>,
+    tcp_thread: Option<TcpThread>,
 }
 
 impl AnyDNS {
@@ -102,6 +173,9 @@ impl AnyDNS {
         for thread in self.threads {
             thread.join()
         };
+        if let Some(tcp_thread) = self.tcp_thread {
+            tcp_thread.join();
+        }
     }
 }
 
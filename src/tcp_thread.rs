@@ -0,0 +1,240 @@
+//! DNS-over-TCP listener, used both to serve clients that require TCP and to
+//! retry queries whose UDP answer from ICANN came back truncated.
+
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{sleep, JoinHandle},
+    time::Duration,
+};
+
+use simple_dns::Packet;
+
+use crate::{
+    custom_handler::{HandlerHolder, LookupOutcome},
+    error::Result,
+    resolver_pool::ResolverPool,
+};
+
+/** Reads a two-byte big-endian length prefix followed by that many bytes (RFC 1035 4.2.2). */
+fn read_message(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+    let mut len_buf = [0u8; 2];
+    stream.read_exact(&mut len_buf)?;
+    let len = u16::from_be_bytes(len_buf) as usize;
+    let mut buf = vec![0u8; len];
+    stream.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn write_message(stream: &mut TcpStream, message: &[u8]) -> std::io::Result<()> {
+    let len = (message.len() as u16).to_be_bytes();
+    stream.write_all(&len)?;
+    stream.write_all(message)?;
+    Ok(())
+}
+
+/** Forwards `query` to `icann_resolver` over a fresh TCP connection and returns its reply. */
+pub fn forward_to_icann_tcp(icann_resolver: &SocketAddr, query: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut stream = TcpStream::connect(icann_resolver)?;
+    write_message(&mut stream, query)?;
+    read_message(&mut stream)
+}
+
+/** Tries each resolver in `resolvers` in turn, starting from the preferred one, until one answers. */
+fn forward_to_icann_tcp_with_failover(resolvers: &ResolverPool, query: &[u8]) -> std::io::Result<Vec<u8>> {
+    let start = resolvers.pick();
+    let mut index = start;
+    loop {
+        match forward_to_icann_tcp(&resolvers.address(index), query) {
+            Ok(reply) => {
+                resolvers.record_success(index);
+                return Ok(reply);
+            }
+            Err(err) => {
+                resolvers.record_timeout(index);
+                let next = resolvers.next(index);
+                if next == start {
+                    return Err(err);
+                }
+                index = next;
+            }
+        }
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, resolvers: ResolverPool, mut handler: HandlerHolder, verbose: bool) {
+    let from = match stream.peer_addr() {
+        Ok(addr) => addr,
+        Err(_) => return,
+    };
+
+    loop {
+        let query = match read_message(&mut stream) {
+            Ok(query) => query,
+            Err(_) => return, // Connection closed or malformed framing.
+        };
+        let Ok(packet) = Packet::parse(&query) else {
+            return;
+        };
+        let Some(question) = packet.questions.get(0).cloned() else {
+            return;
+        };
+
+        let answer = match handler.call_query(&packet, &question, from) {
+            Ok(LookupOutcome::Answer(answers)) => {
+                let mut reply = Packet::new_reply(packet.id());
+                reply.questions.push(question);
+                for answer in answers {
+                    reply.answers.push(answer);
+                }
+                reply.build_bytes_vec().ok()
+            }
+            Ok(LookupOutcome::NxDomain) => {
+                let mut reply = Packet::new_reply(packet.id());
+                reply.questions.push(question);
+                *reply.rcode_mut() = simple_dns::RCODE::NameError;
+                reply.build_bytes_vec().ok()
+            }
+            Ok(LookupOutcome::NxDomainWithAuthority(authority)) => {
+                let mut reply = Packet::new_reply(packet.id());
+                reply.questions.push(question);
+                *reply.rcode_mut() = simple_dns::RCODE::NameError;
+                for record in authority {
+                    reply.name_servers.push(record);
+                }
+                reply.build_bytes_vec().ok()
+            }
+            Ok(LookupOutcome::NoDataWithAuthority(authority)) => {
+                let mut reply = Packet::new_reply(packet.id());
+                reply.questions.push(question);
+                for record in authority {
+                    reply.name_servers.push(record);
+                }
+                reply.build_bytes_vec().ok()
+            }
+            Ok(LookupOutcome::Refused) => {
+                let mut reply = Packet::new_reply(packet.id());
+                reply.questions.push(question);
+                *reply.rcode_mut() = simple_dns::RCODE::Refused;
+                reply.build_bytes_vec().ok()
+            }
+            Ok(LookupOutcome::FallbackToIcann) | Err(_) => None,
+        };
+
+        let reply = match answer {
+            Some(reply) => reply,
+            None => match forward_to_icann_tcp_with_failover(&resolvers, &query) {
+                Ok(reply) => reply,
+                Err(err) => {
+                    if verbose {
+                        eprintln!("TCP forward error {}", err);
+                    }
+                    return;
+                }
+            },
+        };
+
+        if write_message(&mut stream, &reply).is_err() {
+            return;
+        }
+    }
+}
+
+/**
+ * Accepts DNS-over-TCP connections and serves them through the same
+ * handler/ICANN-forward path used by the UDP processor.
+ */
+#[derive(Debug)]
+pub struct TcpThread {
+    stop_signal: Arc<AtomicBool>,
+    handler: JoinHandle<()>,
+}
+
+impl TcpThread {
+    pub fn new(listen: SocketAddr, resolvers: ResolverPool, handler: HandlerHolder, verbose: bool) -> Result<Self> {
+        let listener = TcpListener::bind(listen)?;
+        listener.set_nonblocking(true)?;
+        let stop_signal = Arc::new(AtomicBool::new(false));
+        let thread_stop_signal = stop_signal.clone();
+
+        let handler = std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if thread_stop_signal.load(Ordering::Relaxed) {
+                    return;
+                }
+                match stream {
+                    Ok(stream) => {
+                        let resolvers = resolvers.clone();
+                        let handler = handler.clone();
+                        std::thread::spawn(move || handle_connection(stream, resolvers, handler, verbose));
+                    }
+                    Err(ref err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                        sleep(Duration::from_millis(100));
+                    }
+                    Err(err) => {
+                        if verbose {
+                            eprintln!("TCP accept error {}", err);
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(Self { stop_signal, handler })
+    }
+
+    /** Sends the stop signal to the thread. */
+    pub fn stop(&mut self) {
+        self.stop_signal.store(true, Ordering::Relaxed)
+    }
+
+    /** Stops the thread and waits until it properly terminated. Consumes this instance. */
+    pub fn join(mut self) {
+        self.stop();
+        self.handler.join().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, TcpListener};
+
+    use crate::resolver_pool::ResolverPool;
+
+    use super::{forward_to_icann_tcp, forward_to_icann_tcp_with_failover, read_message, write_message};
+
+    /// Binds a resolver stand-in that echoes back whatever length-prefixed message it receives.
+    fn spawn_echo_resolver() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            if let Ok((mut stream, _)) = listener.accept() {
+                if let Ok(query) = read_message(&mut stream) {
+                    write_message(&mut stream, &query).ok();
+                }
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn forwards_and_reads_back_the_length_prefixed_reply() {
+        let resolver = spawn_echo_resolver();
+        let reply = forward_to_icann_tcp(&resolver, b"hello").unwrap();
+        assert_eq!(reply, b"hello");
+    }
+
+    #[test]
+    fn fails_over_to_the_next_resolver_when_the_first_is_unreachable() {
+        let dead = SocketAddr::from(([127, 0, 0, 1], 1));
+        let live = spawn_echo_resolver();
+        let resolvers = ResolverPool::new(vec![dead, live]);
+
+        let reply = forward_to_icann_tcp_with_failover(&resolvers, b"hello").unwrap();
+        assert_eq!(reply, b"hello");
+    }
+}
@@ -0,0 +1,354 @@
+//! Built-in authoritative handler for local/split-horizon zones, so operators
+//! don't have to hand-write a [`CustomHandler`] for static records.
+
+use std::{
+    collections::BTreeSet,
+    error::Error,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+use simple_dns::{rdata::{RData, CNAME}, Name, Packet, Question, ResourceRecord, CLASS, QTYPE, TYPE};
+
+use crate::custom_handler::{CustomHandler, LookupOutcome};
+
+/// SOA fields for a [`Zone`], as defined in RFC 1035 section 3.3.13.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Soa {
+    pub m_name: String,
+    pub r_name: String,
+    pub serial: u32,
+    pub refresh: i32,
+    pub retry: i32,
+    pub expire: i32,
+    pub minimum: u32,
+}
+
+/// A single record held by a [`Zone`], ordered by `(name, qtype)` so lookups can range over it.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ZoneRecord {
+    pub name: String,
+    pub qtype: ZoneRecordType,
+    pub ttl: u32,
+    pub data: ZoneRecordData,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ZoneRecordType {
+    A,
+    AAAA,
+    CNAME,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ZoneRecordData {
+    A(Ipv4Addr),
+    AAAA(Ipv6Addr),
+    CNAME(String),
+}
+
+impl ZoneRecord {
+    pub fn a(name: impl Into<String>, ttl: u32, address: Ipv4Addr) -> Self {
+        Self { name: name.into(), qtype: ZoneRecordType::A, ttl, data: ZoneRecordData::A(address) }
+    }
+
+    pub fn aaaa(name: impl Into<String>, ttl: u32, address: Ipv6Addr) -> Self {
+        Self { name: name.into(), qtype: ZoneRecordType::AAAA, ttl, data: ZoneRecordData::AAAA(address) }
+    }
+
+    pub fn cname(name: impl Into<String>, ttl: u32, target: impl Into<String>) -> Self {
+        Self { name: name.into(), qtype: ZoneRecordType::CNAME, ttl, data: ZoneRecordData::CNAME(target.into()) }
+    }
+
+    fn to_resource_record(&self) -> Result<ResourceRecord<'static>, Box<dyn Error>> {
+        let name = Name::new(&self.name)?.into_owned();
+        let rdata = match &self.data {
+            ZoneRecordData::A(address) => RData::A((*address).try_into()?),
+            ZoneRecordData::AAAA(address) => RData::AAAA((*address).try_into()?),
+            ZoneRecordData::CNAME(target) => RData::CNAME(CNAME(Name::new(target)?.into_owned())),
+        };
+        Ok(ResourceRecord::new(name, CLASS::IN, self.ttl, rdata))
+    }
+}
+
+/// Qualifies a zone-file name to an FQDN under `domain`, so it can be compared directly
+/// against a queried qname. Names already ending in `.` are treated as already-qualified.
+/// Qualifies `name` into the same no-trailing-dot form `Name::to_string()` produces for
+/// queried names, so zone records compare equal to the qnames they're looked up by.
+fn qualify(name: &str, domain: &str) -> String {
+    let qualified = if name.ends_with('.') {
+        name.to_string()
+    } else {
+        format!("{}.{}", name, domain)
+    };
+    qualified.trim_end_matches('.').to_string()
+}
+
+/// An authoritative local zone: a domain, its SOA, and the records it serves.
+#[derive(Debug, Clone)]
+pub struct Zone {
+    pub domain: String,
+    pub soa: Soa,
+    records: BTreeSet<ZoneRecord>,
+}
+
+impl Zone {
+    pub fn new(domain: impl Into<String>, soa: Soa) -> Self {
+        Self { domain: domain.into(), soa, records: BTreeSet::new() }
+    }
+
+    pub fn with_record(mut self, record: ZoneRecord) -> Self {
+        self.records.insert(record);
+        self
+    }
+
+    /**
+     * Parses a simple declarative zone definition:
+     * ```text
+     * $ORIGIN example.com.
+     * $SOA ns1.example.com. admin.example.com. 1 3600 600 86400 300
+     * www A 120 127.0.0.1
+     * mail CNAME 120 www.example.com.
+     * ```
+     */
+    pub fn parse(definition: &str) -> Result<Self, Box<dyn Error>> {
+        let mut domain: Option<String> = None;
+        let mut soa: Option<Soa> = None;
+        let mut records = BTreeSet::new();
+
+        for line in definition.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["$ORIGIN", name] => domain = Some(name.to_string()),
+                ["$SOA", m_name, r_name, serial, refresh, retry, expire, minimum] => {
+                    soa = Some(Soa {
+                        m_name: m_name.to_string(),
+                        r_name: r_name.to_string(),
+                        serial: serial.parse()?,
+                        refresh: refresh.parse()?,
+                        retry: retry.parse()?,
+                        expire: expire.parse()?,
+                        minimum: minimum.parse()?,
+                    });
+                }
+                [name, "A", ttl, address] => {
+                    let domain = domain.as_deref().ok_or("Record appears before $ORIGIN")?;
+                    records.insert(ZoneRecord::a(qualify(name, domain), ttl.parse()?, address.parse()?));
+                }
+                [name, "AAAA", ttl, address] => {
+                    let domain = domain.as_deref().ok_or("Record appears before $ORIGIN")?;
+                    records.insert(ZoneRecord::aaaa(qualify(name, domain), ttl.parse()?, address.parse()?));
+                }
+                [name, "CNAME", ttl, target] => {
+                    let domain = domain.as_deref().ok_or("Record appears before $ORIGIN")?;
+                    records.insert(ZoneRecord::cname(qualify(name, domain), ttl.parse()?, qualify(target, domain)));
+                }
+                _ => return Err(format!("Invalid zone definition line: {}", line).into()),
+            }
+        }
+
+        let domain = domain.ok_or("Zone definition is missing $ORIGIN")?;
+        let soa = soa.ok_or("Zone definition is missing $SOA")?;
+        Ok(Self { domain, soa, records })
+    }
+
+    fn records_for(&self, qname: &str, qtype: ZoneRecordType) -> Vec<&ZoneRecord> {
+        self.records
+            .iter()
+            .filter(|record| record.name.eq_ignore_ascii_case(qname) && record.qtype == qtype)
+            .collect()
+    }
+
+    /// Whether `qname` has any record at all in this zone, regardless of qtype: used to tell
+    /// NODATA (name exists, wrong qtype) apart from NXDOMAIN (name doesn't exist).
+    fn has_name(&self, qname: &str) -> bool {
+        self.records.iter().any(|record| record.name.eq_ignore_ascii_case(qname))
+    }
+
+    fn soa_record(&self) -> Result<ResourceRecord<'static>, Box<dyn Error>> {
+        let rdata = RData::SOA(simple_dns::rdata::SOA {
+            mname: Name::new(&self.soa.m_name)?.into_owned(),
+            rname: Name::new(&self.soa.r_name)?.into_owned(),
+            serial: self.soa.serial,
+            refresh: self.soa.refresh,
+            retry: self.soa.retry,
+            expire: self.soa.expire,
+            minimum: self.soa.minimum,
+        });
+        Ok(ResourceRecord::new(Name::new(&self.domain)?.into_owned(), CLASS::IN, self.soa.minimum, rdata))
+    }
+
+    /** Resolves `qname`/`qtype` authoritatively, following CNAME chains within this zone. */
+    fn resolve(&self, qname: &str, qtype: ZoneRecordType) -> Result<Vec<ResourceRecord<'static>>, Box<dyn Error>> {
+        let mut answers = vec![];
+        let mut current = qname.to_string();
+        // Bound the chain so a record pointing at itself can't loop forever.
+        for _ in 0..self.records.len().max(1) {
+            let direct = self.records_for(&current, qtype);
+            if !direct.is_empty() {
+                for record in direct {
+                    answers.push(record.to_resource_record()?);
+                }
+                return Ok(answers);
+            }
+
+            let cname = self.records_for(&current, ZoneRecordType::CNAME);
+            match cname.first() {
+                Some(record) => {
+                    answers.push(record.to_resource_record()?);
+                    if let ZoneRecordData::CNAME(target) = &record.data {
+                        current = target.clone();
+                    }
+                }
+                None => break,
+            }
+        }
+        Ok(answers)
+    }
+}
+
+/**
+ * First-class [`CustomHandler`] that answers queries authoritatively from a set of [`Zone`]s,
+ * without operators having to hand-parse and hand-build raw DNS bytes.
+ */
+#[derive(Debug, Clone)]
+pub struct ZoneHandler {
+    zones: Vec<Zone>,
+}
+
+impl ZoneHandler {
+    pub fn new() -> Self {
+        Self { zones: vec![] }
+    }
+
+    pub fn with_zone(mut self, zone: Zone) -> Self {
+        self.zones.push(zone);
+        self
+    }
+
+    fn find_zone(&self, qname: &str) -> Option<&Zone> {
+        self.zones
+            .iter()
+            .find(|zone| qname.eq_ignore_ascii_case(&zone.domain) || qname.to_lowercase().ends_with(&format!(".{}", zone.domain.trim_end_matches('.').to_lowercase())))
+    }
+
+    fn qtype_of(question: &Question) -> Option<ZoneRecordType> {
+        match question.qtype {
+            QTYPE::TYPE(TYPE::A) => Some(ZoneRecordType::A),
+            QTYPE::TYPE(TYPE::AAAA) => Some(ZoneRecordType::AAAA),
+            QTYPE::TYPE(TYPE::CNAME) => Some(ZoneRecordType::CNAME),
+            _ => None,
+        }
+    }
+}
+
+impl Default for ZoneHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Implemented directly against the structured `lookup_query` API rather than the raw
+// `lookup` escape hatch, now that `LookupOutcome` can carry authority records: this avoids
+// a round trip through raw bytes, and the NXDOMAIN/NODATA SOA survives the processor's
+// `call_query` path instead of being dropped by the generic `lookup`-to-`lookup_query` bridge.
+impl CustomHandler for ZoneHandler {
+    fn lookup_query(&mut self, _packet: &Packet, question: &Question, _from: SocketAddr) -> Result<LookupOutcome, Box<dyn Error>> {
+        let qname = question.qname.to_string();
+        let Some(zone) = self.find_zone(&qname) else {
+            return Ok(LookupOutcome::FallbackToIcann);
+        };
+        let Some(qtype) = Self::qtype_of(question) else {
+            return Ok(LookupOutcome::FallbackToIcann);
+        };
+
+        let answers = zone.resolve(&qname, qtype)?;
+        if !answers.is_empty() {
+            return Ok(LookupOutcome::Answer(answers));
+        }
+
+        let authority = vec![zone.soa_record()?];
+        if zone.has_name(&qname) {
+            // NODATA: the name exists in this zone, just not with this qtype.
+            Ok(LookupOutcome::NoDataWithAuthority(authority))
+        } else {
+            Ok(LookupOutcome::NxDomainWithAuthority(authority))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use simple_dns::{Name, Packet, Question, CLASS, QCLASS, QTYPE, TYPE};
+
+    use crate::custom_handler::{CustomHandler, LookupOutcome};
+
+    use super::{Zone, ZoneHandler};
+
+    /// The zone-file example from [`Zone::parse`]'s doc comment.
+    const DEFINITION: &str = "\
+$ORIGIN example.com.
+$SOA ns1.example.com. admin.example.com. 1 3600 600 86400 300
+www A 120 127.0.0.1
+mail CNAME 120 www.example.com.
+";
+
+    fn lookup(handler: &mut ZoneHandler, qname: &str, qtype: TYPE) -> LookupOutcome {
+        let question = Question::new(Name::new(qname).unwrap().into_owned(), QTYPE::TYPE(qtype), QCLASS::CLASS(CLASS::IN), false);
+        let packet = Packet::new_query(1);
+        let from = SocketAddr::from(([127, 0, 0, 1], 0));
+        handler.lookup_query(&packet, &question, from).unwrap()
+    }
+
+    #[test]
+    fn resolves_a_record_from_doc_comment_example() {
+        let zone = Zone::parse(DEFINITION).unwrap();
+        let mut handler = ZoneHandler::new().with_zone(zone);
+
+        match lookup(&mut handler, "www.example.com.", TYPE::A) {
+            LookupOutcome::Answer(answers) => {
+                assert_eq!(answers.len(), 1);
+                assert_eq!(answers[0].name.to_string(), "www.example.com");
+            }
+            other => panic!("expected an answer, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn follows_cname_chain_from_doc_comment_example() {
+        let zone = Zone::parse(DEFINITION).unwrap();
+        let mut handler = ZoneHandler::new().with_zone(zone);
+
+        match lookup(&mut handler, "mail.example.com.", TYPE::A) {
+            LookupOutcome::Answer(answers) => assert_eq!(answers.len(), 2, "expected the CNAME record plus its resolved A record"),
+            other => panic!("expected an answer, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_name_returns_nxdomain_with_soa_authority() {
+        let zone = Zone::parse(DEFINITION).unwrap();
+        let mut handler = ZoneHandler::new().with_zone(zone);
+
+        match lookup(&mut handler, "nope.example.com.", TYPE::A) {
+            LookupOutcome::NxDomainWithAuthority(authority) => assert_eq!(authority.len(), 1),
+            other => panic!("expected NXDOMAIN with SOA authority, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn wrong_qtype_returns_nodata_with_soa_authority() {
+        let zone = Zone::parse(DEFINITION).unwrap();
+        let mut handler = ZoneHandler::new().with_zone(zone);
+
+        match lookup(&mut handler, "www.example.com.", TYPE::AAAA) {
+            LookupOutcome::NoDataWithAuthority(authority) => assert_eq!(authority.len(), 1),
+            other => panic!("expected NODATA with SOA authority, got {:?}", other),
+        }
+    }
+}